@@ -1,24 +1,48 @@
 use actix::{Actor, StreamHandler, AsyncContext, ActorContext};
-use actix_web::{web, App, Error, HttpRequest, HttpResponse, HttpServer};
+use actix::fut::ActorFutureExt;
+use actix_web::{web, web::Bytes, App, Error, HttpRequest, HttpResponse, HttpServer};
 use actix_web_actors::ws;
-use log::{info, warn, error};
 use std::time::{Duration, Instant};
 use uuid::Uuid;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use serde::{Serialize, Deserialize};
-use serde_json;
-use chrono;
 use rand::Rng;
+use tokio::sync::broadcast;
 
 // Constants
 const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
 const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+// Rooms with no connected players and no activity for ROOM_TTL are removed
+const ROOM_TTL: Duration = Duration::from_secs(5 * 60);
+const ROOM_REAPER_INTERVAL: Duration = Duration::from_secs(5);
+// A dropped player keeps their room slot this long, so they can resume or be replaced
+const DISCONNECT_GRACE_PERIOD: Duration = Duration::from_secs(30);
+const MAX_NAME_LENGTH: usize = 24;
+const MAX_PLAYERS_PER_ROOM: usize = 8;
+const PLAYER_MAX_HEALTH: u32 = 100;
+// PlayerUpdate throttling: updates closer together than PLAYER_UPDATE_MIN_INTERVAL are
+// coalesced, and more than PLAYER_UPDATE_BURST_LIMIT within PLAYER_UPDATE_BURST_WINDOW
+// earns a warning; clients warned more than PLAYER_UPDATE_MAX_WARNINGS times are disconnected.
+// Warnings are forgiven after PLAYER_UPDATE_WARNING_RESET without another flood
+const PLAYER_UPDATE_MIN_INTERVAL: Duration = Duration::from_millis(50);
+const PLAYER_UPDATE_BURST_WINDOW: Duration = Duration::from_secs(1);
+const PLAYER_UPDATE_BURST_LIMIT: usize = 60;
+const PLAYER_UPDATE_MAX_WARNINGS: u32 = 3;
+const PLAYER_UPDATE_WARNING_RESET: Duration = Duration::from_secs(60);
+// First byte of a binary WebSocket frame identifying its message type
+const BINARY_PLAYER_UPDATE_TAG: u8 = 0x01;
+// Room-wide chat throttle: engages when more than ROOM_CHAT_BURST_LIMIT messages
+// arrive within ROOM_CHAT_WINDOW, then allows one message per ROOM_CHAT_SLOW_INTERVAL
+// until the volume drops back under half the limit
+const ROOM_CHAT_WINDOW: Duration = Duration::from_secs(10);
+const ROOM_CHAT_BURST_LIMIT: usize = 30;
+const ROOM_CHAT_SLOW_INTERVAL: Duration = Duration::from_secs(2);
 
 // Message types for WebSocket communication
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(tag = "type", content = "payload")]
 enum GameMessage {
-    Join { player_id: Option<String>, room_id: Option<String>, create_room: Option<bool> },
+    Join { player_id: Option<String>, room_id: Option<String>, create_room: Option<bool>, replace: Option<String>, binary: Option<bool>, name: Option<String> },
     Leave { player_id: String },
     Chat { player_id: String, text: String },
     PlayerUpdate { player_id: String, position: Position, action: Option<String> },
@@ -26,10 +50,15 @@ enum GameMessage {
     Error { message: String },
     Ping { time: u64 },
     Pong { time: u64 },
+    SetName { name: String },
+    PlayerRenamed { player_id: String, name: String },
+    SystemMessage { text: String },
+    PlayerJoined { player_id: String, players_count: usize },
+    PlayerLeft { player_id: String, players_count: usize },
 }
 
 // Position type for player and entity coordinates
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 struct Position {
     x: f32,
     y: f32,
@@ -38,7 +67,7 @@ struct Position {
 }
 
 // Entity type for world objects
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 struct Entity {
     id: String,
     entity_type: String,
@@ -46,12 +75,156 @@ struct Entity {
     state: Option<String>,
 }
 
+// Per-player state the room keeps, so it can be handed to a replacement player
+#[derive(Debug, Clone)]
+struct PlayerState {
+    /// Last known position, used to bring late joiners up to date
+    position: Option<Position>,
+    health: u32,
+    /// Display name set by Join or SetName
+    name: Option<String>,
+    /// Whether the player negotiated binary PlayerUpdate frames
+    binary_updates: bool,
+    /// When the player's socket dropped; None while they are connected
+    disconnected_at: Option<Instant>,
+    /// Secret handed to the client at Join, required to reconnect as this player
+    resume_token: String,
+}
+
+impl PlayerState {
+    fn new() -> Self {
+        PlayerState {
+            position: None,
+            health: PLAYER_MAX_HEALTH,
+            name: None,
+            binary_updates: false,
+            disconnected_at: None,
+            resume_token: Uuid::new_v4().to_string(),
+        }
+    }
+}
+
 // Room to track connected players
 struct GameRoom {
     id: String,
     players: Vec<String>,
+    #[allow(dead_code)]
     created_at: Instant,
     last_activity: Instant,
+    /// Arrival times of recent chat messages from anyone in the room
+    chat_history: VecDeque<Instant>,
+    /// Whether the room-wide chat throttle is engaged
+    chat_throttled: bool,
+    /// Time the last chat message was let through
+    last_chat: Option<Instant>,
+    /// Authoritative state of world objects, keyed by entity id
+    entities: HashMap<String, Entity>,
+    /// State of each player in the room, keyed by player id
+    player_states: HashMap<String, PlayerState>,
+}
+
+// Outcome of submitting a chat message to a room's throttle
+#[derive(Debug, PartialEq)]
+enum ChatVerdict {
+    Allowed,
+    ThrottleEngaged,
+    ThrottleLifted,
+    Throttled,
+}
+
+impl GameRoom {
+    fn new(id: &str) -> Self {
+        GameRoom {
+            id: id.to_string(),
+            players: Vec::new(),
+            created_at: Instant::now(),
+            last_activity: Instant::now(),
+            chat_history: VecDeque::new(),
+            chat_throttled: false,
+            last_chat: None,
+            entities: HashMap::new(),
+            player_states: HashMap::new(),
+        }
+    }
+    
+    /// Players with a live connection; slots held through the disconnect grace period don't count
+    fn connected_players(&self) -> usize {
+        self.players.iter()
+            .filter(|player_id| self.player_states.get(*player_id)
+                .map(|state| state.disconnected_at.is_none())
+                .unwrap_or(true))
+            .count()
+    }
+    
+    /// Merge entity updates into the world state, returning only the ones that changed
+    fn apply_entity_updates(&mut self, entities: Vec<Entity>) -> Vec<Entity> {
+        let mut changed = Vec::new();
+        
+        for entity in entities {
+            if entity.state.as_deref() == Some("destroyed") {
+                // Only report removals of entities we actually knew about
+                if self.entities.remove(&entity.id).is_some() {
+                    changed.push(entity);
+                }
+            } else if self.entities.get(&entity.id) != Some(&entity) {
+                self.entities.insert(entity.id.clone(), entity.clone());
+                changed.push(entity);
+            }
+        }
+        
+        if !changed.is_empty() {
+            self.last_activity = Instant::now();
+        }
+        
+        changed
+    }
+    
+    /// Record a chat attempt and decide whether it may be delivered
+    fn record_chat(&mut self, now: Instant) -> ChatVerdict {
+        // Forget messages that fell out of the window
+        while let Some(oldest) = self.chat_history.front() {
+            if now.duration_since(*oldest) > ROOM_CHAT_WINDOW {
+                self.chat_history.pop_front();
+            } else {
+                break;
+            }
+        }
+        
+        // Every attempt counts towards the volume, delivered or not
+        self.chat_history.push_back(now);
+        let volume = self.chat_history.len();
+        
+        let verdict = if !self.chat_throttled {
+            if volume > ROOM_CHAT_BURST_LIMIT {
+                self.chat_throttled = true;
+                return ChatVerdict::ThrottleEngaged;
+            }
+            ChatVerdict::Allowed
+        } else if volume <= ROOM_CHAT_BURST_LIMIT / 2 {
+            self.chat_throttled = false;
+            ChatVerdict::ThrottleLifted
+        } else {
+            let slow_interval_elapsed = self.last_chat
+                .map(|last| now.duration_since(last) >= ROOM_CHAT_SLOW_INTERVAL)
+                .unwrap_or(true);
+            if !slow_interval_elapsed {
+                return ChatVerdict::Throttled;
+            }
+            ChatVerdict::Allowed
+        };
+        
+        self.last_chat = Some(now);
+        verdict
+    }
+}
+
+// Outcome of trying to add a player to a room
+#[derive(Debug, PartialEq)]
+enum JoinResult {
+    Joined,
+    AlreadyInRoom,
+    RoomFull,
+    RoomNotFound,
 }
 
 // Session storage
@@ -78,37 +251,89 @@ impl SessionState {
             return self.create_room();
         }
         
-        let room = GameRoom {
-            id: room_id.clone(),
-            players: Vec::new(),
-            created_at: Instant::now(),
-            last_activity: Instant::now(),
-        };
-        
-        self.rooms.insert(room_id.clone(), room);
+        self.rooms.insert(room_id.clone(), GameRoom::new(&room_id));
         println!("Created new room: {}", room_id);
         room_id
     }
     
-    fn join_room(&mut self, room_id: &str, player_id: &str) -> bool {
-        if let Some(room) = self.rooms.get_mut(room_id) {
-            if !room.players.contains(&player_id.to_string()) {
-                room.players.push(player_id.to_string());
-                room.last_activity = Instant::now();
-                self.player_to_room.insert(player_id.to_string(), room_id.to_string());
-                
-                println!("Player {} joined room {} (Total players: {})", 
-                         player_id, room_id, room.players.len());
-                return true;
-            }
+    fn join_room(&mut self, room_id: &str, player_id: &str) -> JoinResult {
+        let room = match self.rooms.get_mut(room_id) {
+            Some(room) => room,
+            None => return JoinResult::RoomNotFound,
+        };
+        
+        // Players re-sending Join don't take up another slot
+        if room.players.iter().any(|id| id == player_id) {
+            return JoinResult::AlreadyInRoom;
         }
-        false
+        
+        if room.players.len() >= MAX_PLAYERS_PER_ROOM {
+            println!("Player {} cannot join room {}: room is full", player_id, room_id);
+            return JoinResult::RoomFull;
+        }
+        
+        // Moving rooms frees the slot in the old one
+        self.leave_room(player_id);
+        let room = match self.rooms.get_mut(room_id) {
+            Some(room) => room,
+            None => return JoinResult::RoomNotFound,
+        };
+        
+        room.players.push(player_id.to_string());
+        room.player_states.insert(player_id.to_string(), PlayerState::new());
+        room.last_activity = Instant::now();
+        self.player_to_room.insert(player_id.to_string(), room_id.to_string());
+        
+        println!("Player {} joined room {} (Total players: {})", 
+                 player_id, room_id, room.players.len());
+        JoinResult::Joined
+    }
+    
+    /// Hand a disconnected player's slot over to a new player, returning the room id
+    fn replace_player(&mut self, target_id: &str, player_id: &str) -> Option<String> {
+        let room_id = self.player_to_room.get(target_id)?.clone();
+        let room = self.rooms.get(&room_id)?;
+        
+        // Only a player whose socket dropped and who hasn't been reaped yet can be replaced
+        let target_disconnected = room.players.iter().any(|id| id == target_id)
+            && room.player_states.get(target_id)
+                .map(|state| state.disconnected_at.is_some())
+                .unwrap_or(false);
+        if !target_disconnected {
+            return None;
+        }
+        
+        // Don't leave a ghost behind in whatever room the new player was in before
+        self.leave_room(player_id);
+        
+        let room = self.rooms.get_mut(&room_id)?;
+        
+        // Take over the exact slot so the player order is unchanged
+        let slot = room.players.iter().position(|id| id == target_id)?;
+        room.players[slot] = player_id.to_string();
+        room.last_activity = Instant::now();
+        
+        // The new player picks up the old one's position and health
+        let mut state = room.player_states.remove(target_id).unwrap_or_else(PlayerState::new);
+        state.disconnected_at = None;
+        // The name and the resume secret belong to the old player, not their slot
+        state.name = None;
+        state.resume_token = Uuid::new_v4().to_string();
+        room.player_states.insert(player_id.to_string(), state);
+        
+        // The old id no longer belongs to any room
+        self.player_to_room.remove(target_id);
+        self.player_to_room.insert(player_id.to_string(), room_id.clone());
+        
+        println!("Player {} replaced {} in room {} (slot {})", player_id, target_id, room_id, slot);
+        Some(room_id)
     }
     
     fn leave_room(&mut self, player_id: &str) {
         if let Some(room_id) = self.player_to_room.remove(player_id) {
             if let Some(room) = self.rooms.get_mut(&room_id) {
                 room.players.retain(|id| id != player_id);
+                room.player_states.remove(player_id);
                 room.last_activity = Instant::now();
                 
                 println!("Player {} left room {} (Players remaining: {})", 
@@ -116,12 +341,88 @@ impl SessionState {
                 
                 // Remove room if empty
                 if room.players.is_empty() {
-                    println!("Room {} is now empty, will be removed", room_id);
+                    self.rooms.remove(&room_id);
+                    println!("Room {} is now empty, removed", room_id);
                 }
             }
         }
     }
     
+    /// Whether a connection may take over the given player id, either reconnecting to a dropped
+    /// slot or superseding a live session; ids nobody holds are always free to use
+    fn may_claim_player(&self, player_id: &str, resume_token: Option<&str>, connected: bool) -> bool {
+        let state = self.player_to_room.get(player_id)
+            .and_then(|room_id| self.rooms.get(room_id))
+            .and_then(|room| room.player_states.get(player_id));
+        match state {
+            Some(state) => resume_token == Some(state.resume_token.as_str()),
+            None => !connected,
+        }
+    }
+    
+    /// Flag a player whose socket dropped, keeping their slot until the grace period runs out
+    fn mark_disconnected(&mut self, player_id: &str) {
+        let room = self.player_to_room.get(player_id)
+            .and_then(|room_id| self.rooms.get_mut(room_id));
+        if let Some(state) = room.and_then(|room| room.player_states.get_mut(player_id)) {
+            state.disconnected_at = Some(Instant::now());
+        }
+    }
+    
+    /// Remove players who stayed disconnected longer than `grace`, returning
+    /// (room id, player id, players left in the room) for each one
+    fn evict_disconnected_players(&mut self, grace: Duration) -> Vec<(String, String, usize)> {
+        let now = Instant::now();
+        let expired: Vec<(String, String)> = self.rooms.iter()
+            .flat_map(|(room_id, room)| {
+                room.player_states.iter()
+                    .filter(|(_, state)| state.disconnected_at
+                        .map(|at| now.duration_since(at) >= grace)
+                        .unwrap_or(false))
+                    .map(move |(player_id, _)| (room_id.clone(), player_id.clone()))
+            })
+            .collect();
+        
+        expired.into_iter()
+            .filter_map(|(room_id, player_id)| {
+                println!("Player {} did not come back, removing from room {}", player_id, room_id);
+                let (room_id, players_count) = self.remove_player(&player_id)?;
+                Some((room_id, player_id, players_count))
+            })
+            .collect()
+    }
+    
+    /// Take a player out of their room, returning the room id and how many players remain
+    fn remove_player(&mut self, player_id: &str) -> Option<(String, usize)> {
+        let room_id = self.get_player_room(player_id)?;
+        self.leave_room(player_id);
+        let players_count = self.rooms.get(&room_id)
+            .map(|room| room.connected_players())
+            .unwrap_or(0);
+        Some((room_id, players_count))
+    }
+    
+    /// Remove rooms that have gone quiet with nobody connected, returning how many were removed
+    fn reap_stale_rooms(&mut self, ttl: Duration, is_connected: impl Fn(&str) -> bool) -> usize {
+        let now = Instant::now();
+        let rooms_before = self.rooms.len();
+        
+        self.rooms.retain(|room_id, room| {
+            let stale = now.duration_since(room.last_activity) > ttl
+                && !room.players.iter().any(|player_id| is_connected(player_id));
+            if stale {
+                println!("Reaping stale room {} (players: {})", room_id, room.players.len());
+            }
+            !stale
+        });
+        
+        // Drop any player entries that point at rooms which no longer exist
+        let rooms = &self.rooms;
+        self.player_to_room.retain(|_, room_id| rooms.contains_key(room_id));
+        
+        rooms_before - self.rooms.len()
+    }
+    
     fn get_player_room(&self, player_id: &str) -> Option<String> {
         self.player_to_room.get(player_id).cloned()
     }
@@ -131,6 +432,67 @@ impl SessionState {
 struct AppState {
     sessions: actix_web::web::Data<std::sync::Mutex<SessionState>>,
     connections: std::sync::Mutex<HashMap<String, actix::Addr<GameSession>>>,
+    /// Fires once when the server is shutting down; background tasks watch it and exit
+    shutdown: broadcast::Sender<()>,
+}
+
+/// Stop an actor (and every interval running on its context) once shutdown is signaled
+fn stop_on_shutdown<A>(ctx: &mut A::Context, shutdown: &broadcast::Sender<()>)
+where
+    A: Actor,
+    A::Context: AsyncContext<A> + ActorContext,
+{
+    let mut shutdown_rx = shutdown.subscribe();
+    ctx.spawn(
+        actix::fut::wrap_future::<_, A>(async move {
+            // Either a signal or a dropped sender means we are tearing down
+            let _ = shutdown_rx.recv().await;
+        })
+        .map(|_, _act, ctx: &mut A::Context| ctx.stop()),
+    );
+}
+
+/// Background task that periodically evicts stale rooms
+struct RoomReaper {
+    app_state: web::Data<AppState>,
+}
+
+impl Actor for RoomReaper {
+    type Context = actix::Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(ROOM_REAPER_INTERVAL, |act, _ctx| act.reap());
+        stop_on_shutdown::<Self>(ctx, &self.app_state.shutdown);
+    }
+}
+
+impl RoomReaper {
+    fn reap(&self) {
+        self.evict_disconnected(DISCONNECT_GRACE_PERIOD);
+        
+        let mut session_state = self.app_state.sessions.lock().unwrap();
+        let connections = self.app_state.connections.lock().unwrap();
+        
+        let reaped = session_state.reap_stale_rooms(ROOM_TTL, |player_id| {
+            connections.get(player_id).map(|addr| addr.connected()).unwrap_or(false)
+        });
+        
+        if reaped > 0 {
+            println!("Room reaper removed {} room(s), {} remaining", reaped, session_state.rooms.len());
+        }
+    }
+    
+    /// Drop players whose grace period ran out and tell their rooms they left
+    fn evict_disconnected(&self, grace: Duration) {
+        let evicted = {
+            let mut session_state = self.app_state.sessions.lock().unwrap();
+            session_state.evict_disconnected_players(grace)
+        };
+        
+        for (room_id, player_id, players_count) in evicted {
+            announce_player_left(&self.app_state, &room_id, &player_id, players_count);
+        }
+    }
 }
 
 /// WebSocket connection handler
@@ -139,12 +501,24 @@ struct GameSession {
     id: String,
     /// Client must send ping at least once per 10 seconds (CLIENT_TIMEOUT)
     hb: Instant,
-    /// Time of last game state update
+    /// Time the last PlayerUpdate was broadcast
     last_update: Instant,
+    /// Newest throttled PlayerUpdate, sent once the minimum interval has passed
+    pending_update: Option<(Position, Option<String>)>,
+    /// Arrival times of recent PlayerUpdates, for burst detection
+    update_times: VecDeque<Instant>,
+    /// Number of times this client has been warned for flooding updates
+    flood_warnings: u32,
+    /// When the last flood warning was issued
+    last_flood: Option<Instant>,
     /// Reference to app state
     app_state: web::Data<AppState>,
     /// Last reported position
     last_position: Option<Position>,
+    /// Display name chosen by the player
+    name: Option<String>,
+    /// Whether the client asked for PlayerUpdate as binary frames at Join
+    binary_updates: bool,
 }
 
 /// Default implementation for GameSession
@@ -154,11 +528,18 @@ impl Default for GameSession {
             id: Uuid::new_v4().to_string(),
             hb: Instant::now(),
             last_update: Instant::now(),
+            pending_update: None,
+            update_times: VecDeque::new(),
+            flood_warnings: 0,
+            last_flood: None,
             app_state: web::Data::new(AppState {
                 sessions: actix_web::web::Data::new(std::sync::Mutex::new(SessionState::new())),
                 connections: std::sync::Mutex::new(HashMap::new()),
+                shutdown: broadcast::channel(1).0,
             }),
             last_position: None,
+            name: None,
+            binary_updates: false,
         }
     }
 }
@@ -172,11 +553,35 @@ impl Actor for GameSession {
         println!("WebSocket connection established for player: {}", self.id);
         // Start the heartbeat process
         self.heartbeat(ctx);
+        // Close the session cleanly when the server shuts down
+        stop_on_shutdown::<Self>(ctx, &self.app_state.shutdown);
+        // Put a reconnecting player straight back into their room
+        self.resume_session(ctx);
     }
     
-    fn stopped(&mut self, _ctx: &mut Self::Context) {
+    /// Runs after a Close frame, a client timeout, or a dropped socket
+    fn stopped(&mut self, ctx: &mut Self::Context) {
         println!("WebSocket connection closed for player: {}", self.id);
-        // Handle cleanup on disconnect - we'll add session management later
+        
+        // Hold both locks so a reconnect can't register halfway through the cleanup
+        let mut session_state = self.app_state.sessions.lock().unwrap();
+        let mut connections = self.app_state.connections.lock().unwrap();
+        
+        // A reconnect already registered a newer session under this id; it owns the slot now
+        let superseded = connections.get(&self.id)
+            .map(|addr| *addr != ctx.address())
+            .unwrap_or(false);
+        if superseded {
+            println!("Player {} reconnected on a new session, skipping cleanup", self.id);
+            return;
+        }
+        
+        // Drop the connection entry
+        connections.remove(&self.id);
+        println!("Removed connection for player {}, total connections: {}", self.id, connections.len());
+        
+        // Keep the room slot for a while; the reaper removes the player and tells the room
+        session_state.mark_disconnected(&self.id);
     }
 }
 
@@ -215,8 +620,20 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for GameSession {
                 }
             }
             Ok(ws::Message::Binary(bin)) => {
-                println!("Binary message received from player: {}", self.id);
-                ctx.binary(bin);
+                match decode_player_update(&bin) {
+                    Ok(message) => {
+                        self.handle_game_message(message, ctx);
+                    }
+                    Err(err) => {
+                        println!("Error decoding binary message from player {}: {}", self.id, err);
+                        let error_msg = GameMessage::Error { 
+                            message: format!("Invalid binary message: {}", err) 
+                        };
+                        if let Ok(json) = serde_json::to_string(&error_msg) {
+                            ctx.text(json);
+                        }
+                    }
+                }
             }
             Ok(ws::Message::Close(reason)) => {
                 println!("Close message received from player: {}", self.id);
@@ -232,15 +649,61 @@ impl GameSession {
     /// Handle a game-specific message
     fn handle_game_message(&mut self, message: GameMessage, ctx: &mut ws::WebsocketContext<Self>) {
         match message {
-            GameMessage::Join { player_id, room_id, create_room } => {
-                println!("Join request from player {} (create_room: {:?}, room_id: {:?})",
-                         self.id, create_room, room_id);
+            GameMessage::Join { player_id: _, room_id, create_room, replace, binary, name } => {
+                println!("Join request from player {} (create_room: {:?}, room_id: {:?}, replace: {:?}, binary: {:?}, name: {:?})",
+                         self.id, create_room, room_id, replace, binary, name);
+                
+                // A name sent with the join goes through the same checks as SetName
+                if let Some(name) = name {
+                    match sanitize_player_name(&name) {
+                        Some(name) => self.name = Some(name),
+                        None => {
+                            self.send_invalid_name_error(ctx);
+                            return;
+                        }
+                    }
+                }
+                
+                // Clients opt in to binary PlayerUpdate frames; everyone else stays on JSON
+                self.binary_updates = binary.unwrap_or(false);
                 
                 // Get session state
                 let mut session_state = self.app_state.sessions.lock().unwrap();
                 
+                // Room the player was in before this join, if any
+                let previous_room_id = session_state.get_player_room(&self.id);
+                
+                // Player whose slot was taken over by this join, if any
+                let mut replaced_player = None;
+                // Set when the player re-sent Join for a room they are already in
+                let mut already_in_room = false;
+                
                 // Create or join room
-                let final_room_id = if create_room.unwrap_or(false) {
+                let final_room_id = if let Some(target_id) = replace {
+                    // Only a player whose connection dropped can be replaced
+                    let replaced_room_id = if target_id == self.id {
+                        None
+                    } else {
+                        session_state.replace_player(&target_id, &self.id)
+                    };
+                    
+                    match replaced_room_id {
+                        Some(replaced_room_id) => {
+                            replaced_player = Some(target_id);
+                            replaced_room_id
+                        }
+                        None => {
+                            println!("Player {} cannot replace {}", self.id, target_id);
+                            let error_msg = GameMessage::Error {
+                                message: format!("Player {} is not available to replace", target_id)
+                            };
+                            if let Ok(json) = serde_json::to_string(&error_msg) {
+                                ctx.text(json);
+                            }
+                            return;
+                        }
+                    }
+                } else if create_room.unwrap_or(false) {
                     // Create a new room
                     let new_room_id = session_state.create_room();
                     
@@ -251,15 +714,28 @@ impl GameSession {
                     new_room_id
                 } else if let Some(requested_room_id) = room_id.clone() {
                     // Try to join existing room by ID
-                    if session_state.join_room(&requested_room_id, &self.id) {
-                        println!("Player {} joined existing room: {}", self.id, requested_room_id);
-                        requested_room_id
-                    } else {
-                        // Room doesn't exist, create a new one
-                        println!("Room {} not found, creating new room for player {}", requested_room_id, self.id);
-                        let new_room_id = session_state.create_room();
-                        session_state.join_room(&new_room_id, &self.id);
-                        new_room_id
+                    match session_state.join_room(&requested_room_id, &self.id) {
+                        result @ (JoinResult::Joined | JoinResult::AlreadyInRoom) => {
+                            already_in_room = result == JoinResult::AlreadyInRoom;
+                            println!("Player {} joined existing room: {}", self.id, requested_room_id);
+                            requested_room_id
+                        }
+                        JoinResult::RoomFull => {
+                            let error_msg = GameMessage::Error {
+                                message: format!("Room {} is full ({} players max)", requested_room_id, MAX_PLAYERS_PER_ROOM)
+                            };
+                            if let Ok(json) = serde_json::to_string(&error_msg) {
+                                ctx.text(json);
+                            }
+                            return;
+                        }
+                        JoinResult::RoomNotFound => {
+                            // Room doesn't exist, create a new one
+                            println!("Room {} not found, creating new room for player {}", requested_room_id, self.id);
+                            let new_room_id = session_state.create_room();
+                            session_state.join_room(&new_room_id, &self.id);
+                            new_room_id
+                        }
                     }
                 } else {
                     // No room specified, use default behavior - create a new room
@@ -269,48 +745,6 @@ impl GameSession {
                     new_room_id
                 };
                 
-                // Send join response with room ID
-                let response = GameMessage::Join { 
-                    player_id: Some(self.id.clone()),
-                    room_id: Some(final_room_id.clone()), 
-                    create_room: None 
-                };
-                
-                // Convert response to string
-                if let Ok(json) = serde_json::to_string(&response) {
-                    // Parse back to Value to add the player count
-                    if let Ok(mut json_value) = serde_json::from_str::<serde_json::Value>(&json) {
-                        // Get the player count for the room
-                        let player_count = match session_state.rooms.get(&final_room_id) {
-                            Some(room) => room.players.len(),
-                            None => 1, // Fallback to 1 if room data is missing
-                        };
-                        
-                        // Add player count to payload
-                        if let Some(payload) = json_value.get_mut("payload") {
-                            if let Some(obj) = payload.as_object_mut() {
-                                obj.insert("players_count".to_string(), serde_json::json!(player_count));
-                            }
-                        }
-                        
-                        // Send the modified response
-                        ctx.text(json_value.to_string());
-                    } else {
-                        // Fallback to original response
-                        ctx.text(json);
-                    }
-                }
-                
-                // Collect other players in the room
-                let other_players: Vec<String> = if let Some(room) = session_state.rooms.get(&final_room_id) {
-                    room.players.iter()
-                        .filter(|pid| *pid != &self.id)
-                        .map(|pid| pid.clone())
-                        .collect()
-                } else {
-                    Vec::new()
-                };
-                
                 // Make a copy of the room ID
                 let room_id_for_broadcast = final_room_id.clone();
                 
@@ -324,23 +758,68 @@ impl GameSession {
                     });
                 }
                 
+                // Record the player's position in the room, or adopt one inherited from a replaced player
+                if let Some(room) = session_state.rooms.get_mut(&final_room_id) {
+                    let state = room.player_states.entry(self.id.clone()).or_insert_with(PlayerState::new);
+                    state.disconnected_at = None;
+                    if self.name.is_some() {
+                        state.name = self.name.clone();
+                    }
+                    state.binary_updates = self.binary_updates;
+                    match &state.position {
+                        Some(position) => self.last_position = Some(position.clone()),
+                        None => state.position = self.last_position.clone(),
+                    }
+                }
+                
+                // Send join response with room ID and player count
+                let player_count = match session_state.rooms.get(&final_room_id) {
+                    Some(room) => {
+                        self.send_join_response(room, ctx);
+                        room.connected_players()
+                    }
+                    None => 1, // Fallback to 1 if room data is missing
+                };
+                
+                // Joining or replacing into another room pulled the player out of the one they were in
+                let left_room = previous_room_id
+                    .filter(|previous_room_id| *previous_room_id != final_room_id)
+                    .map(|previous_room_id| {
+                        let players_count = session_state.rooms.get(&previous_room_id)
+                            .map(|room| room.connected_players())
+                            .unwrap_or(0);
+                        (previous_room_id, players_count)
+                    });
+                
                 // Release the session state lock
                 drop(session_state);
                 
-                // Send positions of existing players to the new player
-                if !other_players.is_empty() {
-                    // Get the connections map
-                    let connections = self.app_state.connections.lock().unwrap();
-                    
-                    // Send position information for each existing player
-                    for player_id in &other_players {
-                        if let Some(player_session) = connections.get(player_id) {
-                            // Send a request to that player to send their position
-                            let _ = player_session.do_send(SendPositionTo(self.id.clone()));
-                        }
-                    }
+                if let Some((previous_room_id, players_count)) = left_room {
+                    announce_player_left(&self.app_state, &previous_room_id, &self.id, players_count);
+                }
+                
+                // Let the room despawn the avatar that was taken over
+                if let Some(target_id) = replaced_player {
+                    // Counted as a leave followed by the join below
+                    let left_msg = GameMessage::PlayerLeft { player_id: target_id, players_count: player_count.saturating_sub(1) };
+                    self.broadcast_to_room(&room_id_for_broadcast, &left_msg);
+                }
+                
+                // Announce the arrival to everyone already in the room
+                if !already_in_room {
+                    let joined_msg = GameMessage::PlayerJoined { player_id: self.id.clone(), players_count: player_count };
+                    self.broadcast_to_room(&room_id_for_broadcast, &joined_msg);
+                }
+                
+                // Tell the room what to call the new player
+                if let Some(name) = &self.name {
+                    let renamed_msg = GameMessage::PlayerRenamed { player_id: self.id.clone(), name: name.clone() };
+                    self.broadcast_to_room(&room_id_for_broadcast, &renamed_msg);
                 }
                 
+                // Bring the new player up to date with everyone already in the room
+                self.send_room_snapshot(&room_id_for_broadcast, ctx);
+                
                 // Also broadcast the new player's position to all existing players
                 if let Some(position) = &self.last_position {
                     // Create an update message with the new player's position
@@ -356,11 +835,78 @@ impl GameSession {
             }
             GameMessage::Leave { player_id } => {
                 println!("Leave request from player {}", player_id);
+                
+                // Leaving on purpose skips the grace period a dropped socket gets
+                let left = {
+                    let mut session_state = self.app_state.sessions.lock().unwrap();
+                    session_state.remove_player(&self.id)
+                };
+                if let Some((room_id, players_count)) = left {
+                    announce_player_left(&self.app_state, &room_id, &self.id, players_count);
+                }
             }
-            GameMessage::Chat { ref player_id, ref text } => {
-                println!("Chat message from player {}: {}", player_id, text);
-                // Echo chat message back
-                if let Ok(json) = serde_json::to_string(&message) {
+            GameMessage::Chat { player_id, text } => {
+                println!("Chat message from player {}: {}", self.id, text);
+                if player_id != self.id {
+                    println!("Warning: Player {} sent chat claiming to be {}", self.id, player_id);
+                }
+                
+                // Run the message through the room-wide throttle
+                let throttle = {
+                    let mut session_state = self.app_state.sessions.lock().unwrap();
+                    session_state.get_player_room(&self.id).and_then(|room_id| {
+                        session_state.rooms.get_mut(&room_id)
+                            .map(|room| (room_id, room.record_chat(Instant::now())))
+                    })
+                };
+                
+                let room_id = if let Some((room_id, verdict)) = throttle {
+                    let notice = match verdict {
+                        ChatVerdict::Allowed => None,
+                        ChatVerdict::ThrottleLifted => Some("Chat is back to normal speed."),
+                        ChatVerdict::ThrottleEngaged => Some("Chat volume is too high, slow mode enabled for everyone."),
+                        ChatVerdict::Throttled => {
+                            let notice = GameMessage::SystemMessage {
+                                text: "Slow mode is on, your message was not sent.".to_string(),
+                            };
+                            if let Ok(json) = serde_json::to_string(&notice) {
+                                ctx.text(json);
+                            }
+                            return;
+                        }
+                    };
+                    
+                    if let Some(text) = notice {
+                        println!("Room {} chat throttle: {}", room_id, text);
+                        let notice = GameMessage::SystemMessage { text: text.to_string() };
+                        if let Ok(json) = serde_json::to_string(&notice) {
+                            ctx.text(json);
+                        }
+                        self.broadcast_to_room(&room_id, &notice);
+                    }
+                    
+                    // The message that tripped the throttle is dropped
+                    if let ChatVerdict::ThrottleEngaged = verdict {
+                        return;
+                    }
+                    Some(room_id)
+                } else {
+                    None
+                };
+                
+                // Always attribute the message to the sending session
+                let chat_msg = GameMessage::Chat {
+                    player_id: self.id.clone(),
+                    text,
+                };
+                
+                // Deliver to everyone else in the room
+                if let Some(room_id) = room_id {
+                    self.broadcast_to_room(&room_id, &chat_msg);
+                }
+                
+                // Echo back so the sender renders it the same way
+                if let Ok(json) = serde_json::to_string(&chat_msg) {
                     ctx.text(json);
                 }
             }
@@ -371,31 +917,98 @@ impl GameSession {
                     ctx.text(json);
                 }
             }
-            GameMessage::PlayerUpdate { player_id, position, action } => {
+            GameMessage::PlayerUpdate { player_id: _, position, action } => {
+                let now = Instant::now();
+                if !self.record_update_burst(now, ctx) {
+                    return;
+                }
+                
                 // Store the position for future use
                 self.last_position = Some(position.clone());
                 
-                // Get the room for this player
+                // Too soon after the last broadcast: keep only the newest position and send it later
+                let elapsed = now.duration_since(self.last_update);
+                if elapsed < PLAYER_UPDATE_MIN_INTERVAL {
+                    let flush_scheduled = self.pending_update.is_some();
+                    self.pending_update = Some((position, action));
+                    if !flush_scheduled {
+                        ctx.run_later(PLAYER_UPDATE_MIN_INTERVAL - elapsed, |act, _ctx| {
+                            if let Some((position, action)) = act.pending_update.take() {
+                                act.publish_player_update(position, action);
+                            }
+                        });
+                    }
+                    return;
+                }
+                
+                self.pending_update = None;
+                self.publish_player_update(position, action);
+            }
+            GameMessage::SetName { name } => {
+                let name = match sanitize_player_name(&name) {
+                    Some(name) => name,
+                    None => {
+                        self.send_invalid_name_error(ctx);
+                        return;
+                    }
+                };
+                
+                println!("Player {} renamed to {}", self.id, name);
+                self.name = Some(name.clone());
+                
+                let renamed_msg = GameMessage::PlayerRenamed {
+                    player_id: self.id.clone(),
+                    name,
+                };
+                
+                // Confirm to the sender so their UI uses the sanitized name
+                if let Ok(json) = serde_json::to_string(&renamed_msg) {
+                    ctx.text(json);
+                }
+                
+                // Keep the name with the room so snapshots and resumes carry it
                 let room_id = {
-                    let session_state = self.app_state.sessions.lock().unwrap();
-                    session_state.get_player_room(&self.id).clone()
+                    let mut session_state = self.app_state.sessions.lock().unwrap();
+                    let room_id = session_state.get_player_room(&self.id);
+                    let state = room_id.as_ref()
+                        .and_then(|room_id| session_state.rooms.get_mut(room_id))
+                        .and_then(|room| room.player_states.get_mut(&self.id));
+                    if let Some(state) = state {
+                        state.name = self.name.clone();
+                    }
+                    room_id
                 };
                 
                 if let Some(room_id) = room_id {
-                    println!("Received PlayerUpdate from {} in room {}: {:?}", 
-                        self.id, room_id, position);
-                    
-                    // Create a new player update message with the correct player ID
-                    let update_msg = GameMessage::PlayerUpdate {
-                        player_id: self.id.clone(),
-                        position,
-                        action,
-                    };
-                    
-                    // Broadcast to all players in the room except self
-                    self.broadcast_to_room(&room_id, &update_msg);
-                } else {
-                    println!("Warning: Player {} sent position update but is not in any room", self.id);
+                    self.broadcast_to_room(&room_id, &renamed_msg);
+                }
+            }
+            GameMessage::WorldUpdate { entities } => {
+                // Merge into the room's world state
+                let update = {
+                    let mut session_state = self.app_state.sessions.lock().unwrap();
+                    session_state.get_player_room(&self.id).and_then(|room_id| {
+                        session_state.rooms.get_mut(&room_id)
+                            .map(|room| (room_id, room.apply_entity_updates(entities)))
+                    })
+                };
+                
+                match update {
+                    Some((room_id, changed)) => {
+                        if changed.is_empty() {
+                            return;
+                        }
+                        
+                        println!("WorldUpdate from {} in room {}: {} entities changed",
+                            self.id, room_id, changed.len());
+                        
+                        // Only send the delta to the rest of the room
+                        let update_msg = GameMessage::WorldUpdate { entities: changed };
+                        self.broadcast_to_room(&room_id, &update_msg);
+                    }
+                    None => {
+                        println!("Warning: Player {} sent world update but is not in any room", self.id);
+                    }
                 }
             }
             _ => {
@@ -404,36 +1017,302 @@ impl GameSession {
         }
     }
 
-    /// Send a ping message to keep the connection alive
-    fn heartbeat(&self, ctx: &mut ws::WebsocketContext<Self>) {
-        ctx.run_interval(HEARTBEAT_INTERVAL, |act, ctx| {
-            if Instant::now().duration_since(act.hb) > CLIENT_TIMEOUT {
-                println!("Client timeout for player {}, disconnecting!", act.id);
-                ctx.stop();
-                return;
+    /// Broadcast this player's position to the room and remember it for late joiners
+    fn publish_player_update(&mut self, position: Position, action: Option<String>) {
+        self.last_update = Instant::now();
+        
+        // Get the room for this player and cache the position for late joiners
+        let room_id = {
+            let mut session_state = self.app_state.sessions.lock().unwrap();
+            let room_id = session_state.get_player_room(&self.id);
+            if let Some(room) = room_id.as_ref().and_then(|room_id| session_state.rooms.get_mut(room_id)) {
+                room.player_states.entry(self.id.clone())
+                    .or_insert_with(PlayerState::new)
+                    .position = Some(position.clone());
             }
+            room_id
+        };
+        
+        if let Some(room_id) = room_id {
+            println!("Received PlayerUpdate from {} in room {}: {:?}", 
+                self.id, room_id, position);
             
-            ctx.ping(b"");
-        });
+            // Create a new player update message with the correct player ID
+            let update_msg = GameMessage::PlayerUpdate {
+                player_id: self.id.clone(),
+                position,
+                action,
+            };
+            
+            // Broadcast to all players in the room except self
+            self.broadcast_to_room(&room_id, &update_msg);
+        } else {
+            println!("Warning: Player {} sent position update but is not in any room", self.id);
+        }
     }
 
-    /// Broadcast a message to all players in a room except the sender
-    fn broadcast_to_room(&mut self, room_id: &str, message: &GameMessage) {
-        if let Ok(json) = serde_json::to_string(message) {
-            // Get session state
-            let session_state = self.app_state.sessions.lock().unwrap();
-            
-            // Get room players
-            if let Some(room) = session_state.rooms.get(room_id) {
-                // Get connections
-                let connections = self.app_state.connections.lock().unwrap();
-                
-                // Send to all players in room except self
-                for player_id in &room.players {
-                    if player_id != &self.id {
-                        if let Some(addr) = connections.get(player_id) {
-                            let _ = addr.do_send(SendMessage(json.clone()));
-                        }
+    /// Track PlayerUpdate volume, returning false if this update should be dropped as a flood
+    fn record_update_burst(&mut self, now: Instant, ctx: &mut ws::WebsocketContext<Self>) -> bool {
+        while let Some(oldest) = self.update_times.front() {
+            if now.duration_since(*oldest) > PLAYER_UPDATE_BURST_WINDOW {
+                self.update_times.pop_front();
+            } else {
+                break;
+            }
+        }
+        
+        self.update_times.push_back(now);
+        if self.update_times.len() <= PLAYER_UPDATE_BURST_LIMIT {
+            return true;
+        }
+        
+        // Start a fresh window so each burst is only counted once
+        self.update_times.clear();
+        
+        if self.record_flood_warning(now) > PLAYER_UPDATE_MAX_WARNINGS {
+            println!("Player {} kept flooding position updates, disconnecting", self.id);
+            ctx.stop();
+            return false;
+        }
+        
+        println!("Player {} is flooding position updates (warning {}/{})",
+            self.id, self.flood_warnings, PLAYER_UPDATE_MAX_WARNINGS);
+        let error_msg = GameMessage::Error {
+            message: format!("Too many position updates, limit is {} per second", PLAYER_UPDATE_BURST_LIMIT)
+        };
+        if let Ok(json) = serde_json::to_string(&error_msg) {
+            ctx.text(json);
+        }
+        false
+    }
+
+    /// Count a flood warning, forgiving earlier ones after a quiet period, and return the total
+    fn record_flood_warning(&mut self, now: Instant) -> u32 {
+        let quiet = self.last_flood
+            .map(|last| now.duration_since(last) >= PLAYER_UPDATE_WARNING_RESET)
+            .unwrap_or(false);
+        if quiet {
+            self.flood_warnings = 0;
+        }
+        
+        self.last_flood = Some(now);
+        self.flood_warnings += 1;
+        self.flood_warnings
+    }
+
+    /// Restore a reconnected player to the room they were in before the socket dropped.
+    /// The room state decides: a player who still holds a slot is resuming, anyone else is new.
+    fn resume_session(&mut self, ctx: &mut ws::WebsocketContext<Self>) {
+        let app_state = self.app_state.clone();
+        let resumed = {
+            let mut session_state = app_state.sessions.lock().unwrap();
+            let room_id = session_state.get_player_room(&self.id);
+            room_id.and_then(|room_id| {
+                let room = session_state.rooms.get_mut(&room_id)?;
+                if !room.players.contains(&self.id) {
+                    return None;
+                }
+                
+                // Back before the reaper evicted us
+                if let Some(state) = room.player_states.get_mut(&self.id) {
+                    state.disconnected_at = None;
+                    if state.position.is_some() {
+                        self.last_position = state.position.clone();
+                    }
+                    if state.name.is_some() {
+                        self.name = state.name.clone();
+                    }
+                    self.binary_updates = state.binary_updates;
+                }
+                
+                // Same view a fresh joiner gets
+                self.send_join_response(room, ctx);
+                Some(room_id)
+            })
+        };
+        
+        if let Some(room_id) = resumed {
+            println!("Player {} resumed in room {}", self.id, room_id);
+            self.send_room_snapshot(&room_id, ctx);
+            
+            // Anyone who joined while we were away left us out of their snapshot
+            if let Some(position) = self.last_position.clone() {
+                let update_msg = GameMessage::PlayerUpdate {
+                    player_id: self.id.clone(),
+                    position,
+                    action: Some("move".to_string()),
+                };
+                self.broadcast_to_room(&room_id, &update_msg);
+            }
+            if let Some(name) = self.name.clone() {
+                let renamed_msg = GameMessage::PlayerRenamed { player_id: self.id.clone(), name };
+                self.broadcast_to_room(&room_id, &renamed_msg);
+            }
+        }
+    }
+
+    /// Tell this session which room it is in, along with the room's player count and its own health
+    fn send_join_response(&self, room: &GameRoom, ctx: &mut ws::WebsocketContext<Self>) {
+        let player_count = room.connected_players();
+        let state = room.player_states.get(&self.id);
+        let health = state.map(|state| state.health).unwrap_or(PLAYER_MAX_HEALTH);
+        let resume_token = state.map(|state| state.resume_token.clone());
+        
+        let response = GameMessage::Join { 
+            player_id: Some(self.id.clone()),
+            room_id: Some(room.id.clone()), 
+            create_room: None,
+            replace: None,
+            binary: Some(self.binary_updates),
+            name: self.name.clone(),
+        };
+        
+        // Convert response to string
+        if let Ok(json) = serde_json::to_string(&response) {
+            // Parse back to Value to add the player count
+            if let Ok(mut json_value) = serde_json::from_str::<serde_json::Value>(&json) {
+                // Add player count to payload
+                if let Some(payload) = json_value.get_mut("payload") {
+                    if let Some(obj) = payload.as_object_mut() {
+                        obj.insert("players_count".to_string(), serde_json::json!(player_count));
+                        obj.insert("health".to_string(), serde_json::json!(health));
+                        obj.insert("resume_token".to_string(), serde_json::json!(resume_token));
+                    }
+                }
+                
+                // Send the modified response
+                ctx.text(json_value.to_string());
+            } else {
+                // Fallback to original response
+                ctx.text(json);
+            }
+        }
+    }
+
+    /// Send the positions of the other players and the current world state to this session
+    fn send_room_snapshot(&self, room_id: &str, ctx: &mut ws::WebsocketContext<Self>) {
+        let session_state = self.app_state.sessions.lock().unwrap();
+        let room = match session_state.rooms.get(room_id) {
+            Some(room) => room,
+            None => return,
+        };
+        
+        // Players inside their disconnect grace period are left out; they show up again if they resume
+        let others: Vec<(&String, &PlayerState)> = room.players.iter()
+            .filter(|pid| *pid != &self.id)
+            .filter_map(|pid| room.player_states.get(pid).map(|state| (pid, state)))
+            .filter(|(_, state)| state.disconnected_at.is_none())
+            .collect();
+        
+        // One PlayerUpdate per player we have a position for
+        for (player_id, state) in &others {
+            if let Some(position) = &state.position {
+                let update_msg = GameMessage::PlayerUpdate {
+                    player_id: (*player_id).clone(),
+                    position: position.clone(),
+                    action: Some("move".to_string()),
+                };
+                self.send_player_update(&update_msg, ctx);
+            }
+            
+            if let Some(name) = &state.name {
+                let renamed_msg = GameMessage::PlayerRenamed { player_id: (*player_id).clone(), name: name.clone() };
+                if let Ok(json) = serde_json::to_string(&renamed_msg) {
+                    ctx.text(json);
+                }
+            }
+        }
+        
+        // The whole world in a single WorldUpdate
+        if !room.entities.is_empty() {
+            let world_msg = GameMessage::WorldUpdate {
+                entities: room.entities.values().cloned().collect(),
+            };
+            if let Ok(json) = serde_json::to_string(&world_msg) {
+                ctx.text(json);
+            }
+        }
+        
+        println!("Sent room {} snapshot to player {} ({} players, {} entities)",
+            room_id, self.id, others.len(), room.entities.len());
+    }
+
+    /// Send a PlayerUpdate straight to this client in the format it negotiated
+    fn send_player_update(&self, message: &GameMessage, ctx: &mut ws::WebsocketContext<Self>) {
+        match message {
+            GameMessage::PlayerUpdate { player_id, position, action } if self.binary_updates => {
+                ctx.binary(encode_player_update(player_id, position, action.as_deref()));
+            }
+            _ => {
+                if let Ok(json) = serde_json::to_string(message) {
+                    ctx.text(json);
+                }
+            }
+        }
+    }
+
+    /// Tell the client their requested name was rejected
+    fn send_invalid_name_error(&self, ctx: &mut ws::WebsocketContext<Self>) {
+        let error_msg = GameMessage::Error {
+            message: format!("Invalid name: must be 1-{} printable characters", MAX_NAME_LENGTH)
+        };
+        if let Ok(json) = serde_json::to_string(&error_msg) {
+            ctx.text(json);
+        }
+    }
+
+    /// Send a ping message to keep the connection alive
+    fn heartbeat(&self, ctx: &mut ws::WebsocketContext<Self>) {
+        ctx.run_interval(HEARTBEAT_INTERVAL, |act, ctx| {
+            if Instant::now().duration_since(act.hb) > CLIENT_TIMEOUT {
+                println!("Client timeout for player {}, disconnecting!", act.id);
+                ctx.stop();
+                return;
+            }
+            
+            ctx.ping(b"");
+        });
+    }
+
+    /// Broadcast a message to all players in a room except the sender
+    fn broadcast_to_room(&mut self, room_id: &str, message: &GameMessage) {
+        send_to_room(&self.app_state, room_id, message, Some(&self.id));
+    }
+}
+
+/// Let the players still in a room despawn someone who left it
+fn announce_player_left(app_state: &AppState, room_id: &str, player_id: &str, players_count: usize) {
+    let left_msg = GameMessage::PlayerLeft { player_id: player_id.to_string(), players_count };
+    send_to_room(app_state, room_id, &left_msg, Some(player_id));
+}
+
+/// Send a message to every connected player in a room, optionally skipping one of them
+fn send_to_room(app_state: &AppState, room_id: &str, message: &GameMessage, except: Option<&str>) {
+    // Position updates also get a binary frame for clients that negotiated it
+    let binary = match message {
+        GameMessage::PlayerUpdate { player_id, position, action } => {
+            Some(encode_player_update(player_id, position, action.as_deref()))
+        }
+        _ => None,
+    };
+    
+    if let Ok(json) = serde_json::to_string(message) {
+        // Get session state
+        let session_state = app_state.sessions.lock().unwrap();
+        
+        // Get room players
+        if let Some(room) = session_state.rooms.get(room_id) {
+            // Get connections
+            let connections = app_state.connections.lock().unwrap();
+            
+            for player_id in room.players.iter().filter(|pid| Some(pid.as_str()) != except) {
+                if let Some(addr) = connections.get(player_id) {
+                    match &binary {
+                        Some(binary) => addr.do_send(SendPlayerUpdate {
+                            json: json.clone(),
+                            binary: binary.clone(),
+                        }),
+                        None => addr.do_send(SendMessage(json.clone())),
                     }
                 }
             }
@@ -458,41 +1337,166 @@ impl actix::Handler<SendMessage> for GameSession {
     }
 }
 
-// Message type for sending position information to a player
-struct SendPositionTo(String);
+// Message type for sending a PlayerUpdate in whichever format the recipient negotiated
+struct SendPlayerUpdate {
+    json: String,
+    binary: Bytes,
+}
 
-impl actix::Message for SendPositionTo {
+impl actix::Message for SendPlayerUpdate {
     type Result = ();
 }
 
-impl actix::Handler<SendPositionTo> for GameSession {
+impl actix::Handler<SendPlayerUpdate> for GameSession {
     type Result = ();
 
-    fn handle(&mut self, msg: SendPositionTo, ctx: &mut Self::Context) -> Self::Result {
-        // Only send position if we have one
-        if let Some(position) = &self.last_position {
-            // Create a player update message with this player's position
-            let update_msg = GameMessage::PlayerUpdate {
-                player_id: self.id.clone(),
-                position: position.clone(),
-                action: Some("move".to_string()),
-            };
-            
-            // Serialize the update message
-            if let Ok(json) = serde_json::to_string(&update_msg) {
-                // Get the connections map
-                let connections = self.app_state.connections.lock().unwrap();
-                
-                // Send the message directly to the specified player
-                if let Some(addr) = connections.get(&msg.0) {
-                    println!("Sending position update from {} to {}", self.id, msg.0);
-                    let _ = addr.do_send(SendMessage(json));
-                }
-            }
+    fn handle(&mut self, msg: SendPlayerUpdate, ctx: &mut Self::Context) -> Self::Result {
+        if self.binary_updates {
+            ctx.binary(msg.binary);
+        } else {
+            ctx.text(msg.json);
+        }
+    }
+}
+
+// Message type telling a session that its player reconnected on a new socket
+struct Replaced;
+
+impl actix::Message for Replaced {
+    type Result = ();
+}
+
+impl actix::Handler<Replaced> for GameSession {
+    type Result = ();
+
+    fn handle(&mut self, _msg: Replaced, ctx: &mut Self::Context) -> Self::Result {
+        println!("Closing superseded connection for player {}", self.id);
+        ctx.close(Some(ws::CloseReason {
+            code: ws::CloseCode::Normal,
+            description: Some("Reconnected from another session".to_string()),
+        }));
+        ctx.stop();
+    }
+}
+
+/// Errors produced when decoding a binary frame
+#[derive(Debug)]
+enum CodecError {
+    UnknownTag(u8),
+    InvalidLength(usize),
+    InvalidPlayerId,
+    InvalidAction,
+}
+
+impl std::fmt::Display for CodecError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CodecError::UnknownTag(tag) => write!(f, "unknown message tag 0x{:02x}", tag),
+            CodecError::InvalidLength(len) => write!(f, "unexpected frame length {}", len),
+            CodecError::InvalidPlayerId => write!(f, "player id is not valid UTF-8"),
+            CodecError::InvalidAction => write!(f, "action is not valid UTF-8"),
         }
     }
 }
 
+/// Encode a player position as a compact binary frame.
+///
+/// Layout (little-endian): tag `u8`, player id length `u16`, player id UTF-8 bytes,
+/// then `x`, `y`, `z` and `rotation` as `f32`, then action length `u8` and action
+/// UTF-8 bytes. A missing rotation is sent as NaN and a missing action as length 0.
+fn encode_player_update(player_id: &str, position: &Position, action: Option<&str>) -> Bytes {
+    // Over-long strings are cut on a character boundary so the frame stays valid UTF-8
+    let id = truncate_at_char_boundary(player_id, u16::MAX as usize).as_bytes();
+    let action = truncate_at_char_boundary(action.unwrap_or(""), u8::MAX as usize).as_bytes();
+    
+    let mut frame = Vec::with_capacity(3 + id.len() + 16 + 1 + action.len());
+    frame.push(BINARY_PLAYER_UPDATE_TAG);
+    frame.extend_from_slice(&(id.len() as u16).to_le_bytes());
+    frame.extend_from_slice(id);
+    for value in [position.x, position.y, position.z, position.rotation.unwrap_or(f32::NAN)] {
+        frame.extend_from_slice(&value.to_le_bytes());
+    }
+    frame.push(action.len() as u8);
+    frame.extend_from_slice(action);
+    
+    Bytes::from(frame)
+}
+
+/// Longest prefix of `text` that fits in `max_bytes` without splitting a character
+fn truncate_at_char_boundary(text: &str, max_bytes: usize) -> &str {
+    if text.len() <= max_bytes {
+        return text;
+    }
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+/// Decode a binary frame produced by `encode_player_update` into a PlayerUpdate
+fn decode_player_update(frame: &[u8]) -> Result<GameMessage, CodecError> {
+    let (&tag, rest) = frame.split_first().ok_or(CodecError::InvalidLength(frame.len()))?;
+    if tag != BINARY_PLAYER_UPDATE_TAG {
+        return Err(CodecError::UnknownTag(tag));
+    }
+    
+    if rest.len() < 2 {
+        return Err(CodecError::InvalidLength(frame.len()));
+    }
+    let id_len = u16::from_le_bytes([rest[0], rest[1]]) as usize;
+    let rest = &rest[2..];
+    if rest.len() < id_len + 17 {
+        return Err(CodecError::InvalidLength(frame.len()));
+    }
+    let action_len = rest[id_len + 16] as usize;
+    if rest.len() != id_len + 17 + action_len {
+        return Err(CodecError::InvalidLength(frame.len()));
+    }
+    
+    let player_id = std::str::from_utf8(&rest[..id_len])
+        .map_err(|_| CodecError::InvalidPlayerId)?
+        .to_string();
+    let action = std::str::from_utf8(&rest[id_len + 17..])
+        .map_err(|_| CodecError::InvalidAction)?;
+    
+    let mut values = rest[id_len..id_len + 16]
+        .chunks_exact(4)
+        .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]));
+    let mut next = || values.next().unwrap_or(f32::NAN);
+    let (x, y, z, rotation) = (next(), next(), next(), next());
+    
+    Ok(GameMessage::PlayerUpdate {
+        player_id,
+        position: Position {
+            x,
+            y,
+            z,
+            rotation: if rotation.is_nan() { None } else { Some(rotation) },
+        },
+        action: if action.is_empty() { None } else { Some(action.to_string()) },
+    })
+}
+
+/// Normalize a player-supplied display name, returning None if it is not acceptable
+fn sanitize_player_name(name: &str) -> Option<String> {
+    // Drop control characters and collapse runs of whitespace
+    let cleaned: String = name
+        .chars()
+        .filter(|c| !c.is_control())
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    
+    let length = cleaned.chars().count();
+    if length == 0 || length > MAX_NAME_LENGTH {
+        return None;
+    }
+    
+    Some(cleaned)
+}
+
 /// WebSocket route handler
 async fn ws_route(
     req: HttpRequest,
@@ -502,6 +1506,7 @@ async fn ws_route(
     let query = req.query_string();
     let mut player_id = None;
     let mut room_id = None;
+    let mut resume_token = None;
     
     // Parse query parameters
     for pair in query.split('&') {
@@ -511,12 +1516,24 @@ async fn ws_route(
                 player_id = Some(value.to_string());
             } else if key == "roomId" {
                 room_id = Some(value.to_string());
+            } else if key == "resumeToken" {
+                resume_token = Some(value.to_string());
             }
         }
     }
 
-    // Generate player ID if not provided
-    let player_id = player_id.unwrap_or_else(|| Uuid::new_v4().to_string());
+    // Generate player ID if not provided, or if the id belongs to someone who can't vouch for it
+    let player_id = player_id
+        .filter(|player_id| {
+            let session_state = app_state.sessions.lock().unwrap();
+            let connected = app_state.connections.lock().unwrap().contains_key(player_id);
+            let allowed = session_state.may_claim_player(player_id, resume_token.as_deref(), connected);
+            if !allowed {
+                println!("Connection claimed player id {} without its resume token, treating as a new player", player_id);
+            }
+            allowed
+        })
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
     
     println!("New WebSocket connection: player_id={}, room_id={:?}", player_id, room_id);
     
@@ -525,6 +1542,10 @@ async fn ws_route(
         id: player_id.clone(),
         hb: Instant::now(),
         last_update: Instant::now(),
+        pending_update: None,
+        update_times: VecDeque::new(),
+        flood_warnings: 0,
+        last_flood: None,
         app_state: app_state.clone(),
         last_position: Some(Position {
             x: 0.0,
@@ -532,15 +1553,20 @@ async fn ws_route(
             z: 0.0,
             rotation: Some(0.0),
         }),
+        name: None,
+        binary_updates: false,
     };
     
     // Start WebSocket session
-    let (addr, resp) = ws::start_with_addr(session, &req, stream)?;
+    let (addr, resp) = ws::WsResponseBuilder::new(session, &req, stream).start_with_addr()?;
     
-    // Store connection
+    // Store connection, then stop the one it replaces so its cleanup sees the new entry
     {
         let mut connections = app_state.connections.lock().unwrap();
-        connections.insert(player_id.clone(), addr);
+        if let Some(previous) = connections.insert(player_id.clone(), addr) {
+            println!("Player {} reconnected, replacing previous connection", player_id);
+            previous.do_send(Replaced);
+        }
         println!("Stored connection for player {}, total connections: {}", player_id, connections.len());
     }
     
@@ -556,6 +1582,32 @@ async fn health_check() -> impl actix_web::Responder {
     }))
 }
 
+/// Resolve once the process is asked to stop, by Ctrl-C or by SIGTERM from docker/systemd
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+            }
+            Err(err) => {
+                println!("Could not listen for SIGTERM ({}), only Ctrl-C will shut down cleanly", err);
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
 /// Main function
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -566,19 +1618,844 @@ async fn main() -> std::io::Result<()> {
     
     // Create and share the session state
     let session_state = web::Data::new(std::sync::Mutex::new(SessionState::new()));
+    let (shutdown_tx, _) = broadcast::channel(1);
     let app_state = web::Data::new(AppState {
         sessions: session_state.clone(),
         connections: std::sync::Mutex::new(HashMap::new()),
+        shutdown: shutdown_tx.clone(),
     });
     
-    // Start the server
-    HttpServer::new(move || {
+    // Periodically clean up rooms left behind by dropped clients
+    RoomReaper { app_state: app_state.clone() }.start();
+    
+    // Start the server; signals are handled below so background tasks stop first
+    let server = HttpServer::new(move || {
         App::new()
             .app_data(app_state.clone())
             .route("/health", web::get().to(health_check))
             .route("/ws", web::get().to(ws_route))
     })
+    .disable_signals()
     .bind("0.0.0.0:8080")?
-    .run()
-    .await
+    .run();
+    
+    // On Ctrl-C or SIGTERM, tell every background task to exit, then stop the server gracefully
+    let server_handle = server.handle();
+    actix_web::rt::spawn(async move {
+        shutdown_signal().await;
+        println!("Shutdown requested, stopping background tasks...");
+        let _ = shutdown_tx.send(());
+        server_handle.stop(true).await;
+    });
+    
+    server.await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::{SinkExt, StreamExt};
+
+    fn test_app_state() -> web::Data<AppState> {
+        web::Data::new(AppState {
+            sessions: web::Data::new(std::sync::Mutex::new(SessionState::new())),
+            connections: std::sync::Mutex::new(HashMap::new()),
+            shutdown: broadcast::channel(1).0,
+        })
+    }
+
+    fn position(x: f32, z: f32) -> Position {
+        Position { x, y: 0.0, z, rotation: Some(0.0) }
+    }
+
+    fn start_test_server(app_state: web::Data<AppState>) -> actix_test::TestServer {
+        actix_test::start(move || {
+            App::new()
+                .app_data(app_state.clone())
+                .route("/ws", web::get().to(ws_route))
+        })
+    }
+
+    // A websocket client that reads the server's JSON messages one at a time
+    struct TestClient {
+        framed: actix_codec::Framed<awc::BoxedSocket, awc::ws::Codec>,
+    }
+
+    impl TestClient {
+        async fn connect(srv: &mut actix_test::TestServer, player_id: &str) -> Self {
+            Self::connect_with_query(srv, &format!("playerId={}", player_id)).await
+        }
+        
+        /// Reconnect as a player using the resume token from their Join response
+        async fn reconnect(srv: &mut actix_test::TestServer, player_id: &str, resume_token: &str) -> Self {
+            Self::connect_with_query(srv, &format!("playerId={}&resumeToken={}", player_id, resume_token)).await
+        }
+        
+        async fn connect_with_query(srv: &mut actix_test::TestServer, query: &str) -> Self {
+            let url = srv.url(&format!("/ws?{}", query));
+            let (_, framed) = awc::Client::new().ws(url).connect().await.unwrap();
+            TestClient { framed }
+        }
+        
+        async fn send(&mut self, message: serde_json::Value) {
+            self.framed.send(awc::ws::Message::Text(message.to_string().into())).await.unwrap();
+        }
+        
+        /// Skip ahead to the next message of the given type
+        async fn next_of_type(&mut self, message_type: &str) -> serde_json::Value {
+            let read = async {
+                while let Some(frame) = self.framed.next().await {
+                    if let Ok(awc::ws::Frame::Text(text)) = frame {
+                        let value: serde_json::Value = serde_json::from_slice(&text).unwrap();
+                        if value["type"] == message_type {
+                            return value["payload"].clone();
+                        }
+                    }
+                }
+                panic!("connection closed before a {} message arrived", message_type);
+            };
+            tokio::time::timeout(Duration::from_secs(2), read)
+                .await
+                .unwrap_or_else(|_| panic!("no {} message within 2s", message_type))
+        }
+        
+        /// Skip ahead to the next binary frame
+        async fn next_binary(&mut self) -> Bytes {
+            let read = async {
+                while let Some(frame) = self.framed.next().await {
+                    if let Ok(awc::ws::Frame::Binary(bin)) = frame {
+                        return bin;
+                    }
+                }
+                panic!("connection closed before a binary frame arrived");
+            };
+            tokio::time::timeout(Duration::from_secs(2), read)
+                .await
+                .expect("no binary frame within 2s")
+        }
+        
+        /// Count the messages of the given type that arrive within `window`
+        async fn count_of_type(&mut self, message_type: &str, window: Duration) -> usize {
+            let mut count = 0;
+            let _ = tokio::time::timeout(window, async {
+                while let Some(frame) = self.framed.next().await {
+                    if let Ok(awc::ws::Frame::Text(text)) = frame {
+                        let value: serde_json::Value = serde_json::from_slice(&text).unwrap();
+                        if value["type"] == message_type {
+                            count += 1;
+                        }
+                    }
+                }
+            })
+            .await;
+            count
+        }
+        
+        /// Create or join a room and wait for the server to confirm it
+        async fn join(&mut self, room_id: Option<&str>) -> serde_json::Value {
+            let payload = match room_id {
+                Some(room_id) => serde_json::json!({ "room_id": room_id }),
+                None => serde_json::json!({ "create_room": true }),
+            };
+            self.send(serde_json::json!({ "type": "Join", "payload": payload })).await;
+            self.next_of_type("Join").await
+        }
+    }
+
+    async fn wait_until(mut condition: impl FnMut() -> bool) {
+        let waited = tokio::time::timeout(Duration::from_secs(2), async {
+            while !condition() {
+                actix_rt::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await;
+        assert!(waited.is_ok(), "condition not met within 2s");
+    }
+
+    #[test]
+    fn replacement_inherits_slot_and_state() {
+        let mut state = SessionState::new();
+        let room_id = state.create_room();
+        state.join_room(&room_id, "alice");
+        state.join_room(&room_id, "bob");
+        {
+            let alice = state.rooms.get_mut(&room_id).unwrap().player_states.get_mut("alice").unwrap();
+            alice.health = 40;
+            alice.position = Some(position(3.0, 4.0));
+        }
+        state.mark_disconnected("alice");
+        
+        assert_eq!(state.replace_player("alice", "carol"), Some(room_id.clone()));
+        
+        let room = &state.rooms[&room_id];
+        assert_eq!(room.players, vec!["carol".to_string(), "bob".to_string()]);
+        assert_eq!(room.player_states["carol"].health, 40);
+        assert_eq!(room.player_states["carol"].position, Some(position(3.0, 4.0)));
+        assert!(room.player_states["carol"].disconnected_at.is_none());
+        assert!(!room.player_states.contains_key("alice"));
+        assert_eq!(state.get_player_room("alice"), None);
+        assert_eq!(state.get_player_room("carol"), Some(room_id));
+    }
+
+    #[test]
+    fn replacement_leaves_previous_room() {
+        let mut state = SessionState::new();
+        let target_room = state.create_room();
+        state.join_room(&target_room, "alice");
+        state.join_room(&target_room, "bob");
+        let previous_room = state.create_room();
+        state.join_room(&previous_room, "carol");
+        state.join_room(&previous_room, "dave");
+        state.mark_disconnected("alice");
+        
+        state.replace_player("alice", "carol");
+        
+        assert_eq!(state.rooms[&previous_room].players, vec!["dave".to_string()]);
+        assert!(!state.rooms[&previous_room].player_states.contains_key("carol"));
+        assert_eq!(state.get_player_room("carol"), Some(target_room));
+    }
+
+    #[test]
+    fn replacing_unknown_player_keeps_current_room() {
+        let mut state = SessionState::new();
+        let room_id = state.create_room();
+        state.join_room(&room_id, "carol");
+        
+        assert_eq!(state.replace_player("nobody", "carol"), None);
+        assert_eq!(state.get_player_room("carol"), Some(room_id));
+    }
+
+    #[test]
+    fn claiming_a_player_id_needs_its_resume_token() {
+        let mut state = SessionState::new();
+        let room_id = state.create_room();
+        state.join_room(&room_id, "alice");
+        let token = state.rooms[&room_id].player_states["alice"].resume_token.clone();
+        
+        assert!(state.may_claim_player("alice", Some(&token), true));
+        assert!(!state.may_claim_player("alice", None, false));
+        assert!(!state.may_claim_player("alice", Some("guess"), true));
+        
+        // Ids nobody holds are free, live ones outside a room are not
+        assert!(state.may_claim_player("bob", None, false));
+        assert!(!state.may_claim_player("bob", None, true));
+    }
+
+    #[test]
+    fn connected_player_cannot_be_replaced() {
+        let mut state = SessionState::new();
+        let room_id = state.create_room();
+        state.join_room(&room_id, "alice");
+        
+        assert_eq!(state.replace_player("alice", "carol"), None);
+        assert_eq!(state.rooms[&room_id].players, vec!["alice".to_string()]);
+    }
+
+    #[test]
+    fn disconnected_player_is_evicted_after_grace_period() {
+        let mut state = SessionState::new();
+        let room_id = state.create_room();
+        state.join_room(&room_id, "alice");
+        state.join_room(&room_id, "bob");
+        state.mark_disconnected("bob");
+        
+        // Still inside the grace period: the slot is kept
+        assert!(state.evict_disconnected_players(Duration::from_secs(60)).is_empty());
+        assert_eq!(state.rooms[&room_id].players.len(), 2);
+        
+        let evicted = state.evict_disconnected_players(Duration::ZERO);
+        assert_eq!(evicted, vec![(room_id.clone(), "bob".to_string(), 1)]);
+        assert_eq!(state.rooms[&room_id].players, vec!["alice".to_string()]);
+        assert_eq!(state.get_player_room("bob"), None);
+    }
+
+    #[test]
+    fn player_names_are_sanitized() {
+        assert_eq!(sanitize_player_name("  Sir \t  Robin \n"), Some("Sir Robin".to_string()));
+        assert_eq!(sanitize_player_name("Ro\u{7}bin"), Some("Robin".to_string()));
+        assert_eq!(sanitize_player_name(&"a".repeat(MAX_NAME_LENGTH)), Some("a".repeat(MAX_NAME_LENGTH)));
+    }
+
+    #[test]
+    fn invalid_player_names_are_rejected() {
+        assert_eq!(sanitize_player_name(""), None);
+        assert_eq!(sanitize_player_name(" \t\n\u{0}"), None);
+        assert_eq!(sanitize_player_name(&"a".repeat(MAX_NAME_LENGTH + 1)), None);
+    }
+
+    #[test]
+    fn binary_player_update_round_trips() {
+        for (pos, action) in [
+            (position(1.5, -2.0), Some("jump")),
+            (Position { x: 0.0, y: 7.0, z: 0.0, rotation: None }, None),
+        ] {
+            let frame = encode_player_update("player-1", &pos, action);
+            match decode_player_update(&frame) {
+                Ok(GameMessage::PlayerUpdate { player_id, position, action: decoded }) => {
+                    assert_eq!(player_id, "player-1");
+                    assert_eq!(position, pos);
+                    assert_eq!(decoded.as_deref(), action);
+                }
+                other => panic!("unexpected decode result: {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn long_multibyte_action_is_cut_on_a_char_boundary() {
+        // Two-byte characters: the 255-byte limit falls mid-character, so 127 of them survive
+        let action = "\u{e9}".repeat(200);
+        let frame = encode_player_update("player-1", &position(1.0, 2.0), Some(&action));
+        match decode_player_update(&frame) {
+            Ok(GameMessage::PlayerUpdate { action: Some(decoded), .. }) => {
+                assert_eq!(decoded, "\u{e9}".repeat(127));
+            }
+            other => panic!("unexpected decode result: {:?}", other),
+        }
+        
+        // Four-byte characters in the player id are cut the same way
+        let player_id = "\u{1f47e}".repeat(20_000);
+        let frame = encode_player_update(&player_id, &position(1.0, 2.0), None);
+        match decode_player_update(&frame) {
+            Ok(GameMessage::PlayerUpdate { player_id: decoded, .. }) => {
+                assert_eq!(decoded, "\u{1f47e}".repeat(u16::MAX as usize / 4));
+            }
+            other => panic!("unexpected decode result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn truncated_binary_frame_is_rejected() {
+        let frame = encode_player_update("player-1", &position(1.0, 2.0), Some("move"));
+        for len in [0, 2, frame.len() - 5, frame.len() - 1] {
+            assert!(matches!(decode_player_update(&frame[..len]), Err(CodecError::InvalidLength(_))),
+                "length {} decoded", len);
+        }
+        
+        let mut padded = frame.to_vec();
+        padded.push(0);
+        assert!(matches!(decode_player_update(&padded), Err(CodecError::InvalidLength(_))));
+    }
+
+    #[test]
+    fn binary_frame_with_unknown_tag_is_rejected() {
+        let mut frame = encode_player_update("player-1", &position(1.0, 2.0), None).to_vec();
+        frame[0] = 0x7f;
+        assert!(matches!(decode_player_update(&frame), Err(CodecError::UnknownTag(0x7f))));
+    }
+
+    #[test]
+    fn flood_warnings_are_forgiven_after_quiet_period() {
+        let mut session = GameSession::default();
+        let start = Instant::now();
+        
+        for expected in 1..=PLAYER_UPDATE_MAX_WARNINGS {
+            assert_eq!(session.record_flood_warning(start), expected);
+        }
+        
+        // Floods close together keep adding up
+        let soon = start + PLAYER_UPDATE_WARNING_RESET / 2;
+        assert_eq!(session.record_flood_warning(soon), PLAYER_UPDATE_MAX_WARNINGS + 1);
+        
+        // A quiet spell starts the count over
+        assert_eq!(session.record_flood_warning(soon + PLAYER_UPDATE_WARNING_RESET), 1);
+    }
+
+    fn entity(id: &str, x: f32, state: Option<&str>) -> Entity {
+        Entity {
+            id: id.to_string(),
+            entity_type: "crate".to_string(),
+            position: position(x, 0.0),
+            state: state.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn entity_updates_report_only_changes() {
+        let mut room = GameRoom::new("1000");
+        
+        // New entities are stored and reported
+        let changed = room.apply_entity_updates(vec![entity("a", 1.0, None), entity("b", 2.0, None)]);
+        assert_eq!(changed, vec![entity("a", 1.0, None), entity("b", 2.0, None)]);
+        assert_eq!(room.entities.len(), 2);
+        
+        // Resending an entity unchanged is not reported, a moved one is
+        let changed = room.apply_entity_updates(vec![entity("a", 1.0, None), entity("b", 5.0, None)]);
+        assert_eq!(changed, vec![entity("b", 5.0, None)]);
+        assert_eq!(room.entities["b"], entity("b", 5.0, None));
+    }
+
+    #[test]
+    fn destroyed_entities_are_removed_once() {
+        let mut room = GameRoom::new("1000");
+        room.apply_entity_updates(vec![entity("a", 1.0, None)]);
+        
+        let changed = room.apply_entity_updates(vec![entity("a", 1.0, Some("destroyed"))]);
+        assert_eq!(changed, vec![entity("a", 1.0, Some("destroyed"))]);
+        assert!(room.entities.is_empty());
+        
+        // Destroying something the room never knew about, or destroying it twice, is not broadcast
+        let changed = room.apply_entity_updates(vec![
+            entity("a", 1.0, Some("destroyed")),
+            entity("ghost", 0.0, Some("destroyed")),
+        ]);
+        assert!(changed.is_empty());
+        assert!(room.entities.is_empty());
+    }
+
+    #[test]
+    fn room_chat_throttle_engages_on_high_volume() {
+        let mut room = GameRoom::new("1000");
+        let start = Instant::now();
+        
+        for _ in 0..ROOM_CHAT_BURST_LIMIT {
+            assert_eq!(room.record_chat(start), ChatVerdict::Allowed);
+        }
+        assert_eq!(room.record_chat(start), ChatVerdict::ThrottleEngaged);
+        assert!(room.chat_throttled);
+    }
+
+    #[test]
+    fn room_chat_throttle_slows_then_lifts() {
+        let mut room = GameRoom::new("1000");
+        let start = Instant::now();
+        for _ in 0..=ROOM_CHAT_BURST_LIMIT {
+            room.record_chat(start);
+        }
+        assert!(room.chat_throttled);
+        
+        // Inside the slow interval everything is held back
+        assert_eq!(room.record_chat(start + Duration::from_millis(100)), ChatVerdict::Throttled);
+        
+        // One message gets through per slow interval while volume stays high
+        let later = start + ROOM_CHAT_SLOW_INTERVAL;
+        assert_eq!(room.record_chat(later), ChatVerdict::Allowed);
+        assert_eq!(room.record_chat(later), ChatVerdict::Throttled);
+        
+        // Once the burst has aged out of the window the throttle lifts
+        let quiet = later + ROOM_CHAT_WINDOW + Duration::from_secs(1);
+        assert_eq!(room.record_chat(quiet), ChatVerdict::ThrottleLifted);
+        assert!(!room.chat_throttled);
+        assert_eq!(room.record_chat(quiet), ChatVerdict::Allowed);
+    }
+
+    #[actix_rt::test]
+    async fn reaper_stops_after_shutdown_signal() {
+        let app_state = test_app_state();
+        let addr = RoomReaper { app_state: app_state.clone() }.start();
+        
+        // Wait for the reaper to subscribe in started()
+        while app_state.shutdown.receiver_count() == 0 {
+            actix_rt::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert!(addr.connected());
+        
+        app_state.shutdown.send(()).unwrap();
+        
+        let stopped = tokio::time::timeout(Duration::from_secs(1), async {
+            while addr.connected() {
+                actix_rt::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await;
+        assert!(stopped.is_ok(), "reaper still running after shutdown");
+    }
+
+    #[actix_rt::test]
+    async fn dropped_session_is_removed_from_room() {
+        let app_state = test_app_state();
+        let mut srv = start_test_server(app_state.clone());
+        
+        let mut alice = TestClient::connect(&mut srv, "alice").await;
+        let room_id = alice.join(None).await["room_id"].as_str().unwrap().to_string();
+        let mut bob = TestClient::connect(&mut srv, "bob").await;
+        bob.join(Some(&room_id)).await;
+        assert_eq!(app_state.sessions.lock().unwrap().rooms[&room_id].players.len(), 2);
+        
+        drop(bob);
+        wait_until(|| !app_state.connections.lock().unwrap().contains_key("bob")).await;
+        
+        // The slot survives the drop until the grace period runs out
+        {
+            let session_state = app_state.sessions.lock().unwrap();
+            let room = &session_state.rooms[&room_id];
+            assert!(room.players.contains(&"bob".to_string()));
+            assert!(room.player_states["bob"].disconnected_at.is_some());
+        }
+        
+        RoomReaper { app_state: app_state.clone() }.evict_disconnected(Duration::ZERO);
+        
+        assert_eq!(app_state.sessions.lock().unwrap().rooms[&room_id].players, vec!["alice".to_string()]);
+        let left = alice.next_of_type("PlayerLeft").await;
+        assert_eq!(left["player_id"], "bob");
+        assert_eq!(left["players_count"], 1);
+    }
+
+    #[actix_rt::test]
+    async fn chat_reaches_other_players_as_the_sender() {
+        let app_state = test_app_state();
+        let mut srv = start_test_server(app_state.clone());
+        
+        let mut alice = TestClient::connect(&mut srv, "alice").await;
+        let room_id = alice.join(None).await["room_id"].as_str().unwrap().to_string();
+        let mut bob = TestClient::connect(&mut srv, "bob").await;
+        bob.join(Some(&room_id)).await;
+        
+        // A spoofed player_id must not survive the fan-out
+        alice.send(serde_json::json!({
+            "type": "Chat",
+            "payload": { "player_id": "bob", "text": "hello" }
+        })).await;
+        
+        let chat = bob.next_of_type("Chat").await;
+        assert_eq!(chat["player_id"], "alice");
+        assert_eq!(chat["text"], "hello");
+    }
+
+    #[actix_rt::test]
+    async fn leave_removes_player_and_notifies_room() {
+        let app_state = test_app_state();
+        let mut srv = start_test_server(app_state.clone());
+        
+        let mut alice = TestClient::connect(&mut srv, "alice").await;
+        let room_id = alice.join(None).await["room_id"].as_str().unwrap().to_string();
+        let mut bob = TestClient::connect(&mut srv, "bob").await;
+        bob.join(Some(&room_id)).await;
+        
+        bob.send(serde_json::json!({ "type": "Leave", "payload": { "player_id": "bob" } })).await;
+        
+        let left = alice.next_of_type("PlayerLeft").await;
+        assert_eq!(left["player_id"], "bob");
+        assert_eq!(left["players_count"], 1);
+        let session_state = app_state.sessions.lock().unwrap();
+        assert_eq!(session_state.rooms[&room_id].players, vec!["alice".to_string()]);
+        assert_eq!(session_state.get_player_room("bob"), None);
+    }
+
+    #[actix_rt::test]
+    async fn rename_is_broadcast_to_the_room() {
+        let app_state = test_app_state();
+        let mut srv = start_test_server(app_state.clone());
+        
+        let mut alice = TestClient::connect(&mut srv, "alice").await;
+        let room_id = alice.join(None).await["room_id"].as_str().unwrap().to_string();
+        let mut bob = TestClient::connect(&mut srv, "bob").await;
+        bob.join(Some(&room_id)).await;
+        
+        alice.send(serde_json::json!({ "type": "SetName", "payload": { "name": "  Sir   Robin " } })).await;
+        
+        let renamed = bob.next_of_type("PlayerRenamed").await;
+        assert_eq!(renamed["player_id"], "alice");
+        assert_eq!(renamed["name"], "Sir Robin");
+        assert_eq!(alice.next_of_type("PlayerRenamed").await["name"], "Sir Robin");
+        let session_state = app_state.sessions.lock().unwrap();
+        assert_eq!(session_state.rooms[&room_id].player_states["alice"].name.as_deref(), Some("Sir Robin"));
+    }
+
+    #[actix_rt::test]
+    async fn invalid_name_is_rejected() {
+        let app_state = test_app_state();
+        let mut srv = start_test_server(app_state.clone());
+        
+        let mut alice = TestClient::connect(&mut srv, "alice").await;
+        alice.join(None).await;
+        
+        alice.send(serde_json::json!({ "type": "SetName", "payload": { "name": " \u{7} " } })).await;
+        assert!(alice.next_of_type("Error").await["message"].as_str().unwrap().contains("Invalid name"));
+        
+        alice.send(serde_json::json!({
+            "type": "Join",
+            "payload": { "create_room": true, "name": "x".repeat(MAX_NAME_LENGTH + 1) }
+        })).await;
+        assert!(alice.next_of_type("Error").await["message"].as_str().unwrap().contains("Invalid name"));
+    }
+
+    #[actix_rt::test]
+    async fn late_joiner_learns_names_from_snapshot() {
+        let app_state = test_app_state();
+        let mut srv = start_test_server(app_state.clone());
+        
+        let mut alice = TestClient::connect(&mut srv, "alice").await;
+        alice.send(serde_json::json!({
+            "type": "Join",
+            "payload": { "create_room": true, "name": "Robin" }
+        })).await;
+        let joined = alice.next_of_type("Join").await;
+        assert_eq!(joined["name"], "Robin");
+        let room_id = joined["room_id"].as_str().unwrap().to_string();
+        
+        let mut bob = TestClient::connect(&mut srv, "bob").await;
+        bob.join(Some(&room_id)).await;
+        
+        let renamed = bob.next_of_type("PlayerRenamed").await;
+        assert_eq!(renamed["player_id"], "alice");
+        assert_eq!(renamed["name"], "Robin");
+    }
+
+    #[actix_rt::test]
+    async fn reconnect_resumes_slot_despite_old_session_stopping_late() {
+        let app_state = test_app_state();
+        let mut srv = start_test_server(app_state.clone());
+        
+        let mut alice = TestClient::connect(&mut srv, "alice").await;
+        alice.send(serde_json::json!({
+            "type": "Join",
+            "payload": { "create_room": true, "binary": true, "name": "Robin" }
+        })).await;
+        let joined = alice.next_of_type("Join").await;
+        let room_id = joined["room_id"].as_str().unwrap().to_string();
+        let resume_token = joined["resume_token"].as_str().unwrap().to_string();
+        let old_addr = app_state.connections.lock().unwrap()["alice"].clone();
+        
+        // Reconnect while the old session is still alive; it only stops once it sees Replaced
+        let mut resumed = TestClient::reconnect(&mut srv, "alice", &resume_token).await;
+        let rejoined = resumed.next_of_type("Join").await;
+        assert_eq!(rejoined["room_id"], room_id.as_str());
+        assert_eq!(rejoined["binary"], true);
+        assert_eq!(rejoined["name"], "Robin");
+        
+        // The old session's stopped() runs after the new one registered and must leave it alone
+        wait_until(|| !old_addr.connected()).await;
+        let session_state = app_state.sessions.lock().unwrap();
+        let connections = app_state.connections.lock().unwrap();
+        assert_eq!(session_state.rooms[&room_id].players, vec!["alice".to_string()]);
+        assert!(session_state.rooms[&room_id].player_states["alice"].disconnected_at.is_none());
+        assert!(connections["alice"] != old_addr);
+        assert!(connections["alice"].connected());
+    }
+
+    #[actix_rt::test]
+    async fn reconnect_after_drop_resumes_slot() {
+        let app_state = test_app_state();
+        let mut srv = start_test_server(app_state.clone());
+        
+        let mut alice = TestClient::connect(&mut srv, "alice").await;
+        let joined = alice.join(None).await;
+        let room_id = joined["room_id"].as_str().unwrap().to_string();
+        let resume_token = joined["resume_token"].as_str().unwrap().to_string();
+        
+        drop(alice);
+        wait_until(|| !app_state.connections.lock().unwrap().contains_key("alice")).await;
+        
+        let mut resumed = TestClient::reconnect(&mut srv, "alice", &resume_token).await;
+        assert_eq!(resumed.next_of_type("Join").await["room_id"], room_id.as_str());
+        let session_state = app_state.sessions.lock().unwrap();
+        assert!(session_state.rooms[&room_id].player_states["alice"].disconnected_at.is_none());
+    }
+
+    #[actix_rt::test]
+    async fn snapshot_uses_negotiated_binary_format() {
+        let app_state = test_app_state();
+        let mut srv = start_test_server(app_state.clone());
+        
+        let mut alice = TestClient::connect(&mut srv, "alice").await;
+        let room_id = alice.join(None).await["room_id"].as_str().unwrap().to_string();
+        
+        let mut bob = TestClient::connect(&mut srv, "bob").await;
+        bob.send(serde_json::json!({
+            "type": "Join",
+            "payload": { "room_id": room_id, "binary": true }
+        })).await;
+        
+        match decode_player_update(&bob.next_binary().await) {
+            Ok(GameMessage::PlayerUpdate { player_id, action, .. }) => {
+                assert_eq!(player_id, "alice");
+                assert_eq!(action.as_deref(), Some("move"));
+            }
+            other => panic!("unexpected snapshot frame: {:?}", other),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn update_flood_broadcasts_stay_bounded() {
+        let app_state = test_app_state();
+        let mut srv = start_test_server(app_state.clone());
+        
+        let mut alice = TestClient::connect(&mut srv, "alice").await;
+        let room_id = alice.join(None).await["room_id"].as_str().unwrap().to_string();
+        let mut bob = TestClient::connect(&mut srv, "bob").await;
+        bob.join(Some(&room_id)).await;
+        // Alice's position from the join snapshot
+        bob.next_of_type("PlayerUpdate").await;
+        
+        let sent = 200;
+        for i in 0..sent {
+            alice.send(serde_json::json!({
+                "type": "PlayerUpdate",
+                "payload": {
+                    "player_id": "alice",
+                    "position": { "x": i as f32, "y": 0.0, "z": 0.0, "rotation": 0.0 },
+                    "action": "move"
+                }
+            })).await;
+        }
+        
+        // At most one broadcast per minimum interval, plus the leading and trailing update
+        let window = Duration::from_millis(500);
+        let received = bob.count_of_type("PlayerUpdate", window).await;
+        let bound = (window.as_millis() / PLAYER_UPDATE_MIN_INTERVAL.as_millis()) as usize + 2;
+        assert!(received >= 1, "no updates got through");
+        assert!(received <= bound, "{} of {} updates broadcast, expected at most {}", received, sent, bound);
+    }
+
+    #[test]
+    fn joining_another_room_leaves_the_previous_one() {
+        let mut state = SessionState::new();
+        let first_room = state.create_room();
+        state.join_room(&first_room, "alice");
+        state.join_room(&first_room, "bob");
+        let second_room = state.create_room();
+        
+        assert_eq!(state.join_room(&second_room, "bob"), JoinResult::Joined);
+        
+        assert_eq!(state.rooms[&first_room].players, vec!["alice".to_string()]);
+        assert!(!state.rooms[&first_room].player_states.contains_key("bob"));
+        assert_eq!(state.rooms[&second_room].players, vec!["bob".to_string()]);
+        assert_eq!(state.get_player_room("bob"), Some(second_room));
+    }
+
+    #[actix_rt::test]
+    async fn switching_rooms_notifies_the_old_room() {
+        let app_state = test_app_state();
+        let mut srv = start_test_server(app_state.clone());
+        
+        let mut alice = TestClient::connect(&mut srv, "alice").await;
+        let first_room = alice.join(None).await["room_id"].as_str().unwrap().to_string();
+        let mut bob = TestClient::connect(&mut srv, "bob").await;
+        bob.join(Some(&first_room)).await;
+        
+        let second_room = bob.join(None).await["room_id"].as_str().unwrap().to_string();
+        assert_ne!(second_room, first_room);
+        
+        let left = alice.next_of_type("PlayerLeft").await;
+        assert_eq!(left["player_id"], "bob");
+        assert_eq!(left["players_count"], 1);
+        let session_state = app_state.sessions.lock().unwrap();
+        assert_eq!(session_state.rooms[&first_room].players, vec!["alice".to_string()]);
+        assert_eq!(session_state.rooms[&second_room].players, vec!["bob".to_string()]);
+    }
+
+    #[test]
+    fn full_room_rejects_newcomers_but_not_members() {
+        let mut state = SessionState::new();
+        let room_id = state.create_room();
+        for i in 0..MAX_PLAYERS_PER_ROOM {
+            assert_eq!(state.join_room(&room_id, &format!("player-{}", i)), JoinResult::Joined);
+        }
+        
+        assert_eq!(state.join_room(&room_id, "late"), JoinResult::RoomFull);
+        assert_eq!(state.get_player_room("late"), None);
+        assert_eq!(state.join_room(&room_id, "player-0"), JoinResult::AlreadyInRoom);
+        assert_eq!(state.rooms[&room_id].players.len(), MAX_PLAYERS_PER_ROOM);
+    }
+
+    #[test]
+    fn moving_out_frees_a_slot_in_a_full_room() {
+        let mut state = SessionState::new();
+        let room_id = state.create_room();
+        for i in 0..MAX_PLAYERS_PER_ROOM {
+            state.join_room(&room_id, &format!("player-{}", i));
+        }
+        let other_room = state.create_room();
+        state.join_room(&other_room, "player-0");
+        
+        assert_eq!(state.join_room(&room_id, "late"), JoinResult::Joined);
+    }
+
+    #[actix_rt::test]
+    async fn join_is_refused_once_the_room_is_full() {
+        let app_state = test_app_state();
+        let mut srv = start_test_server(app_state.clone());
+        
+        let mut host = TestClient::connect(&mut srv, "player-0").await;
+        let room_id = host.join(None).await["room_id"].as_str().unwrap().to_string();
+        let mut members = Vec::new();
+        for i in 1..MAX_PLAYERS_PER_ROOM {
+            let mut member = TestClient::connect(&mut srv, &format!("player-{}", i)).await;
+            member.join(Some(&room_id)).await;
+            members.push(member);
+        }
+        
+        let mut late = TestClient::connect(&mut srv, "late").await;
+        late.send(serde_json::json!({ "type": "Join", "payload": { "room_id": room_id } })).await;
+        assert!(late.next_of_type("Error").await["message"].as_str().unwrap().contains("is full"));
+        
+        // A member re-sending Join is not turned away by the cap
+        let rejoined = host.join(Some(&room_id)).await;
+        assert_eq!(rejoined["room_id"], room_id.as_str());
+        assert_eq!(rejoined["players_count"], MAX_PLAYERS_PER_ROOM);
+        assert_eq!(app_state.sessions.lock().unwrap().rooms[&room_id].players.len(), MAX_PLAYERS_PER_ROOM);
+    }
+
+    #[actix_rt::test]
+    async fn player_id_without_resume_token_cannot_take_over() {
+        let app_state = test_app_state();
+        let mut srv = start_test_server(app_state.clone());
+        
+        let mut alice = TestClient::connect(&mut srv, "alice").await;
+        let room_id = alice.join(None).await["room_id"].as_str().unwrap().to_string();
+        let alice_addr = app_state.connections.lock().unwrap()["alice"].clone();
+        
+        let mut intruder = TestClient::connect(&mut srv, "alice").await;
+        let joined = intruder.join(None).await;
+        
+        // The intruder is a new player; alice keeps her session and her room
+        assert_ne!(joined["player_id"], "alice");
+        assert_ne!(joined["room_id"], room_id.as_str());
+        assert!(alice_addr.connected());
+        assert!(app_state.connections.lock().unwrap()["alice"] == alice_addr);
+        assert_eq!(app_state.sessions.lock().unwrap().rooms[&room_id].players, vec!["alice".to_string()]);
+    }
+
+    #[test]
+    fn disconnected_players_are_not_counted() {
+        let mut state = SessionState::new();
+        let room_id = state.create_room();
+        state.join_room(&room_id, "alice");
+        state.join_room(&room_id, "bob");
+        state.mark_disconnected("bob");
+        
+        let room = &state.rooms[&room_id];
+        assert_eq!(room.players.len(), 2);
+        assert_eq!(room.connected_players(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn late_joiner_does_not_see_disconnected_players() {
+        let app_state = test_app_state();
+        let mut srv = start_test_server(app_state.clone());
+        
+        let mut alice = TestClient::connect(&mut srv, "alice").await;
+        let room_id = alice.join(None).await["room_id"].as_str().unwrap().to_string();
+        let mut bob = TestClient::connect(&mut srv, "bob").await;
+        bob.send(serde_json::json!({
+            "type": "Join",
+            "payload": { "room_id": room_id, "name": "Bob" }
+        })).await;
+        bob.next_of_type("Join").await;
+        drop(bob);
+        wait_until(|| !app_state.connections.lock().unwrap().contains_key("bob")).await;
+        
+        let mut carol = TestClient::connect(&mut srv, "carol").await;
+        let joined = carol.join(Some(&room_id)).await;
+        assert_eq!(joined["players_count"], 2);
+        assert_eq!(alice.next_of_type("PlayerJoined").await["players_count"], 2);
+        
+        // Only alice is in the snapshot; a Ping fences off the end of it
+        carol.send(serde_json::json!({ "type": "Ping", "payload": { "time": 1 } })).await;
+        let mut seen = Vec::new();
+        loop {
+            let frame = carol.framed.next().await.unwrap().unwrap();
+            if let awc::ws::Frame::Text(text) = frame {
+                let value: serde_json::Value = serde_json::from_slice(&text).unwrap();
+                if value["type"] == "Pong" {
+                    break;
+                }
+                if value["type"] == "PlayerUpdate" || value["type"] == "PlayerRenamed" {
+                    seen.push(value["payload"]["player_id"].as_str().unwrap().to_string());
+                }
+            }
+        }
+        assert_eq!(seen, vec!["alice".to_string()]);
+    }
 }